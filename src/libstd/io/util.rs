@@ -13,6 +13,7 @@
 use prelude::*;
 use cmp;
 use io;
+use mem;
 use owned::Box;
 use slice::bytes::MutableByteVector;
 
@@ -55,6 +56,41 @@ impl<R: Reader> Reader for LimitReader<R> {
     }
 }
 
+/// Wraps a `Writer`, limiting the number of bytes that can be written to it.
+pub struct LimitWriter<W> {
+    limit: uint,
+    inner: W
+}
+
+impl<W: Writer> LimitWriter<W> {
+    /// Creates a new `LimitWriter`
+    pub fn new(w: W, limit: uint) -> LimitWriter<W> {
+        LimitWriter { limit: limit, inner: w }
+    }
+
+    /// Consumes the `LimitWriter`, returning the underlying `Writer`.
+    pub fn unwrap(self) -> W { self.inner }
+
+    /// Returns the number of bytes that can be written before the
+    /// `LimitWriter` will return EOF.
+    ///
+    /// # Note
+    ///
+    /// The writer may reach EOF after writing fewer bytes than indicated by
+    /// this method if the underlying writer reaches EOF.
+    pub fn limit(&self) -> uint { self.limit }
+}
+
+impl<W: Writer> Writer for LimitWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::IoResult<()> {
+        if buf.len() > self.limit {
+            return Err(io::standard_error(io::EndOfFile));
+        }
+
+        self.inner.write(buf).map(|()| { self.limit -= buf.len(); })
+    }
+}
+
 /// A `Writer` which ignores bytes written to it, like /dev/null.
 pub struct NullWriter;
 
@@ -63,14 +99,34 @@ impl Writer for NullWriter {
     fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> { Ok(()) }
 }
 
+/// A `Reader` which returns an infinite stream of a single fill byte, like
+/// `/dev/zero` generalized to any byte.
+pub struct RepeatReader {
+    byte: u8,
+}
+
+impl RepeatReader {
+    /// Creates a new `RepeatReader` which fills every read with `byte`.
+    pub fn new(byte: u8) -> RepeatReader {
+        RepeatReader { byte: byte }
+    }
+}
+
+impl Reader for RepeatReader {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::IoResult<uint> {
+        buf.set_memory(self.byte);
+        Ok(buf.len())
+    }
+}
+
 /// A `Reader` which returns an infinite stream of 0 bytes, like /dev/zero.
 pub struct ZeroReader;
 
 impl Reader for ZeroReader {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> io::IoResult<uint> {
-        buf.set_memory(0);
-        Ok(buf.len())
+        RepeatReader::new(0).read(buf)
     }
 }
 
@@ -84,35 +140,102 @@ impl Reader for NullReader {
     }
 }
 
+/// Describes how a `MultiWriter` should react when one of its writers fails.
+pub enum MultiWriterPolicy {
+    /// Stop at the first failing writer and return its error immediately,
+    /// leaving any writers after it untouched.
+    FailFast,
+    /// Write to every writer regardless of failures, returning an aggregate
+    /// error afterwards if any writer failed. This is the default, and
+    /// matches the historical behavior of `MultiWriter`, which always wrote
+    /// to every writer and never let one failing sink stop the others.
+    ContinueAll,
+    /// Write to every writer regardless of failures; a writer is removed
+    /// from the set the first time it fails, so later writes skip it.
+    DropFailed,
+}
+
 /// A `Writer` which multiplexes writes to a set of `Writers`.
 pub struct MultiWriter {
-    writers: Vec<Box<Writer>>
+    writers: Vec<Box<Writer>>,
+    policy: MultiWriterPolicy,
 }
 
 impl MultiWriter {
-    /// Creates a new `MultiWriter`
+    /// Creates a new `MultiWriter` using the `ContinueAll` policy, matching
+    /// the historical behavior of always writing to every writer.
     pub fn new(writers: Vec<Box<Writer>>) -> MultiWriter {
-        MultiWriter { writers: writers }
+        MultiWriter::with_policy(writers, ContinueAll)
+    }
+
+    /// Creates a new `MultiWriter` using the given failure `policy`.
+    pub fn with_policy(writers: Vec<Box<Writer>>, policy: MultiWriterPolicy) -> MultiWriter {
+        MultiWriter { writers: writers, policy: policy }
+    }
+
+    fn each_writer(&mut self, f: |&mut Box<Writer>| -> io::IoResult<()>) -> io::IoResult<()> {
+        match self.policy {
+            FailFast => {
+                for writer in self.writers.mut_iter() {
+                    try!(f(writer));
+                }
+                Ok(())
+            }
+            ContinueAll => {
+                let mut errs = vec!();
+                for writer in self.writers.mut_iter() {
+                    match f(writer) {
+                        Ok(()) => {}
+                        Err(e) => errs.push(e),
+                    }
+                }
+                aggregate(errs)
+            }
+            DropFailed => {
+                let mut errs = vec!();
+                let mut i = 0;
+                while i < self.writers.len() {
+                    match f(self.writers.get_mut(i)) {
+                        Ok(()) => { i += 1; }
+                        Err(e) => {
+                            errs.push(e);
+                            self.writers.remove(i);
+                        }
+                    }
+                }
+                aggregate(errs)
+            }
+        }
+    }
+}
+
+/// Collapses per-writer errors collected by `ContinueAll`/`DropFailed` into
+/// a single `IoResult`.
+fn aggregate(mut errs: Vec<io::IoError>) -> io::IoResult<()> {
+    if errs.len() == 0 {
+        Ok(())
+    } else if errs.len() == 1 {
+        Err(errs.pop().unwrap())
+    } else {
+        let kind = errs.get(0).kind;
+        let detail = errs.iter().map(|e| format!("{}", e)).collect::<Vec<String>>().connect("; ");
+        Err(io::IoError {
+            kind: kind,
+            desc: "multiple writers failed",
+            detail: Some(detail),
+        })
     }
 }
 
 impl Writer for MultiWriter {
     #[inline]
     fn write(&mut self, buf: &[u8]) -> io::IoResult<()> {
-        let mut ret = Ok(());
-        for writer in self.writers.mut_iter() {
-            ret = ret.and(writer.write(buf));
-        }
-        return ret;
+        self.each_writer(|writer| writer.write(buf))
     }
 
     #[inline]
     fn flush(&mut self) -> io::IoResult<()> {
-        let mut ret = Ok(());
-        for writer in self.writers.mut_iter() {
-            ret = ret.and(writer.flush());
-        }
-        return ret;
+        self.each_writer(|writer| writer.flush())
     }
 }
 
@@ -183,16 +306,146 @@ impl<R: Reader, W: Writer> Reader for TeeReader<R, W> {
     }
 }
 
-/// Copies all data from a `Reader` to a `Writer`.
-pub fn copy<R: Reader, W: Writer>(r: &mut R, w: &mut W) -> io::IoResult<()> {
+/// Splits an underlying `Reader` into records terminated by a delimiter
+/// byte, without allocating a new `Reader` per record. Records are read with
+/// `next_segment`; `SplitReader` does not itself implement `Reader`.
+///
+/// Internally it keeps a `DEFAULT_BUF_SIZE` fill buffer and locates the
+/// delimiter with a word-at-a-time scan rather than a byte-at-a-time loop.
+pub struct SplitReader<R> {
+    inner: R,
+    delim: u8,
+    buf: Vec<u8>,
+    pos: uint,
+    cap: uint,
+    eof: bool,
+}
+
+impl<R: Reader> SplitReader<R> {
+    /// Creates a new `SplitReader` which splits `r` into records terminated
+    /// by `delim`.
+    pub fn new(r: R, delim: u8) -> SplitReader<R> {
+        SplitReader {
+            inner: r,
+            delim: delim,
+            buf: Vec::from_elem(super::DEFAULT_BUF_SIZE, 0u8),
+            pos: 0,
+            cap: 0,
+            eof: false,
+        }
+    }
+
+    /// Appends the next record, with the delimiter stripped, onto `buf`.
+    ///
+    /// Returns `Ok(true)` if a record was appended, or `Ok(false)` if the
+    /// underlying reader is exhausted and no more records remain.
+    pub fn next_segment(&mut self, buf: &mut Vec<u8>) -> io::IoResult<bool> {
+        let start_len = buf.len();
+        let mut found = false;
+        loop {
+            if self.pos == self.cap {
+                if self.eof {
+                    break;
+                }
+                match self.inner.read(self.buf.as_mut_slice()) {
+                    Ok(n) => {
+                        self.cap = n;
+                        self.pos = 0;
+                    }
+                    Err(ref e) if e.kind == io::EndOfFile => {
+                        self.eof = true;
+                        self.cap = 0;
+                        self.pos = 0;
+                    }
+                    Err(e) => return Err(e),
+                }
+                continue;
+            }
+
+            let avail = self.buf.slice(self.pos, self.cap);
+            match find_byte(avail, self.delim) {
+                Some(i) => {
+                    buf.push_all(avail.slice_to(i));
+                    self.pos += i + 1;
+                    found = true;
+                    break;
+                }
+                None => {
+                    buf.push_all(avail);
+                    self.pos = self.cap;
+                }
+            }
+        }
+        Ok(found || buf.len() > start_len)
+    }
+}
+
+/// Returns the index of the first occurrence of `byte` in `buf`, scanning a
+/// `uint` at a time via the classic SWAR zero-byte test, with a byte-at-a-time
+/// fallback for the unaligned head and tail.
+fn find_byte(buf: &[u8], byte: u8) -> Option<uint> {
+    let len = buf.len();
+    let ptr = buf.as_ptr();
+    let usize_bytes = mem::size_of::<uint>();
+
+    let mut offset = 0;
+    while offset < len && (ptr as uint + offset) % usize_bytes != 0 {
+        if buf[offset] == byte {
+            return Some(offset);
+        }
+        offset += 1;
+    }
+
+    let repeated_byte = repeat_byte(byte);
+    while offset + usize_bytes <= len {
+        let word = unsafe { *(ptr.offset(offset as int) as *const uint) };
+        if contains_zero_byte(word ^ repeated_byte) {
+            break;
+        }
+        offset += usize_bytes;
+    }
+
+    while offset < len {
+        if buf[offset] == byte {
+            return Some(offset);
+        }
+        offset += 1;
+    }
+
+    None
+}
+
+/// Repeats `b` in every byte lane of a `uint`.
+fn repeat_byte(b: u8) -> uint {
+    let mut rep = b as uint;
+    let mut shift = 8u;
+    while shift < mem::size_of::<uint>() * 8 {
+        rep = (rep << shift) | rep;
+        shift <<= 1;
+    }
+    rep
+}
+
+/// Tests whether any byte lane of `x` is zero.
+fn contains_zero_byte(x: uint) -> bool {
+    let lo = repeat_byte(0x01);
+    let hi = repeat_byte(0x80);
+    (x - lo) & !x & hi != 0
+}
+
+/// Copies all data from a `Reader` to a `Writer`, returning the number of
+/// bytes that were copied.
+pub fn copy<R: Reader, W: Writer>(r: &mut R, w: &mut W) -> io::IoResult<u64> {
     let mut buf = [0, ..super::DEFAULT_BUF_SIZE];
+    let mut total = 0u64;
     loop {
         let len = match r.read(buf) {
             Ok(len) => len,
-            Err(ref e) if e.kind == io::EndOfFile => return Ok(()),
+            Err(ref e) if e.kind == io::EndOfFile => return Ok(total),
             Err(e) => return Err(e),
         };
         try!(w.write(buf.slice_to(len)));
+        total += len as u64;
     }
 }
 
@@ -234,6 +487,38 @@ mod test {
         assert_eq!(0, r.limit());
     }
 
+    #[test]
+    fn test_limit_writer_unlimited() {
+        let mut w = MemWriter::new();
+        {
+            let mut w = LimitWriter::new(w.by_ref(), 4);
+            w.write([0, 1, 2]).unwrap();
+        }
+        assert_eq!(vec!(0, 1, 2), w.unwrap());
+    }
+
+    #[test]
+    fn test_limit_writer_limited() {
+        let mut w = MemWriter::new();
+        {
+            let mut w = LimitWriter::new(w.by_ref(), 2);
+            w.write([0, 1]).unwrap();
+            assert!(w.write([2]).is_err());
+        }
+        assert_eq!(vec!(0, 1), w.unwrap());
+    }
+
+    #[test]
+    fn test_limit_writer_limit() {
+        let w = MemWriter::new();
+        let mut w = LimitWriter::new(w, 3);
+        assert_eq!(3, w.limit());
+        w.write([0]).unwrap();
+        assert_eq!(2, w.limit());
+        w.write([1, 2]).unwrap();
+        assert_eq!(0, w.limit());
+    }
+
     #[test]
     fn test_null_writer() {
         let mut s = NullWriter;
@@ -250,6 +535,14 @@ mod test {
         assert_eq!(box [0, 0, 0], buf);
     }
 
+    #[test]
+    fn test_repeat_reader() {
+        let mut s = RepeatReader::new(5);
+        let mut buf = box [0, 0, 0];
+        assert_eq!(s.read(buf), Ok(3));
+        assert_eq!(box [5, 5, 5], buf);
+    }
+
     #[test]
     fn test_null_reader() {
         let mut r = NullReader;
@@ -285,6 +578,138 @@ mod test {
         assert_eq!(2, unsafe { flushes });
     }
 
+    #[test]
+    fn test_multi_writer_new_writes_to_all_on_failure() {
+        static mut first_writes: uint = 0;
+        static mut third_writes: uint = 0;
+
+        struct First;
+        impl Writer for First {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                unsafe { first_writes += 1 }
+                Ok(())
+            }
+        }
+
+        struct Failing;
+        impl Writer for Failing {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                Err(io::standard_error(io::OtherIoError))
+            }
+        }
+
+        struct Third;
+        impl Writer for Third {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                unsafe { third_writes += 1 }
+                Ok(())
+            }
+        }
+
+        // `new` must preserve the historical behavior: every writer is
+        // written to even after one fails, unlike `FailFast`.
+        let mut multi = MultiWriter::new(vec!(box First as Box<Writer>,
+                                              box Failing as Box<Writer>,
+                                              box Third as Box<Writer>));
+        assert!(multi.write([1]).is_err());
+        assert_eq!(1, unsafe { first_writes });
+        assert_eq!(1, unsafe { third_writes });
+    }
+
+    #[test]
+    fn test_multi_writer_fail_fast() {
+        static mut first_writes: uint = 0;
+        static mut third_writes: uint = 0;
+
+        struct First;
+        impl Writer for First {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                unsafe { first_writes += 1 }
+                Ok(())
+            }
+        }
+
+        struct Failing;
+        impl Writer for Failing {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                Err(io::standard_error(io::OtherIoError))
+            }
+        }
+
+        struct Third;
+        impl Writer for Third {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                unsafe { third_writes += 1 }
+                Ok(())
+            }
+        }
+
+        let mut multi = MultiWriter::with_policy(
+            vec!(box First as Box<Writer>, box Failing as Box<Writer>, box Third as Box<Writer>),
+            FailFast);
+        assert!(multi.write([1]).is_err());
+        assert_eq!(1, unsafe { first_writes });
+        assert_eq!(0, unsafe { third_writes });
+    }
+
+    #[test]
+    fn test_multi_writer_continue_all() {
+        static mut a_writes: uint = 0;
+        static mut c_writes: uint = 0;
+
+        struct A;
+        impl Writer for A {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                unsafe { a_writes += 1 }
+                Ok(())
+            }
+        }
+
+        struct Failing;
+        impl Writer for Failing {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                Err(io::standard_error(io::OtherIoError))
+            }
+        }
+
+        struct C;
+        impl Writer for C {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                unsafe { c_writes += 1 }
+                Ok(())
+            }
+        }
+
+        let mut multi = MultiWriter::with_policy(
+            vec!(box A as Box<Writer>, box Failing as Box<Writer>, box C as Box<Writer>),
+            ContinueAll);
+        assert!(multi.write([1]).is_err());
+        assert_eq!(1, unsafe { a_writes });
+        assert_eq!(1, unsafe { c_writes });
+    }
+
+    #[test]
+    fn test_multi_writer_drop_failed() {
+        static mut bad_writes: uint = 0;
+
+        struct Failing;
+        impl Writer for Failing {
+            fn write(&mut self, _buf: &[u8]) -> io::IoResult<()> {
+                unsafe { bad_writes += 1 }
+                Err(io::standard_error(io::OtherIoError))
+            }
+        }
+
+        let mut multi = MultiWriter::with_policy(vec!(box Failing as Box<Writer>), DropFailed);
+        assert!(multi.write([1]).is_err());
+        assert_eq!(1, unsafe { bad_writes });
+
+        // The failing writer was dropped, so later writes no longer reach it
+        // and succeed with no writers left to fail.
+        assert!(multi.write([1]).is_ok());
+        assert_eq!(1, unsafe { bad_writes });
+    }
+
     #[test]
     fn test_chained_reader() {
         let rs = vec!(MemReader::new(vec!(0, 1)), MemReader::new(vec!()),
@@ -302,11 +727,52 @@ mod test {
         assert_eq!(vec!(0, 1, 2), w.unwrap());
     }
 
+    #[test]
+    fn test_split_reader() {
+        let input = vec!('a' as u8, ',' as u8, 'b' as u8, 'c' as u8, ',' as u8,
+                          ',' as u8, 'd' as u8);
+        let mut r = SplitReader::new(MemReader::new(input), ',' as u8);
+
+        let mut buf = Vec::new();
+        assert_eq!(r.next_segment(&mut buf), Ok(true));
+        assert_eq!(vec!('a' as u8), buf);
+
+        let mut buf = Vec::new();
+        assert_eq!(r.next_segment(&mut buf), Ok(true));
+        assert_eq!(vec!('b' as u8, 'c' as u8), buf);
+
+        let mut buf = Vec::new();
+        assert_eq!(r.next_segment(&mut buf), Ok(true));
+        assert_eq!(Vec::<u8>::new(), buf);
+
+        let mut buf = Vec::new();
+        assert_eq!(r.next_segment(&mut buf), Ok(true));
+        assert_eq!(vec!('d' as u8), buf);
+
+        let mut buf = Vec::new();
+        assert_eq!(r.next_segment(&mut buf), Ok(false));
+        assert_eq!(Vec::<u8>::new(), buf);
+    }
+
+    #[test]
+    fn test_split_reader_no_trailing_delim() {
+        let input = vec!('a' as u8, 'b' as u8, 'c' as u8);
+        let mut r = SplitReader::new(MemReader::new(input), '\n' as u8);
+
+        let mut buf = Vec::new();
+        assert_eq!(r.next_segment(&mut buf), Ok(true));
+        assert_eq!(vec!('a' as u8, 'b' as u8, 'c' as u8), buf);
+
+        let mut buf = Vec::new();
+        assert_eq!(r.next_segment(&mut buf), Ok(false));
+    }
+
     #[test]
     fn test_copy() {
         let mut r = MemReader::new(vec!(0, 1, 2, 3, 4));
         let mut w = MemWriter::new();
-        copy(&mut r, &mut w).unwrap();
+        let n = copy(&mut r, &mut w).unwrap();
+        assert_eq!(5u64, n);
         assert_eq!(vec!(0, 1, 2, 3, 4), w.unwrap());
     }
 }